@@ -0,0 +1,248 @@
+// Copyright 2017 Luis Pabón <lpabon@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! # Attribute-based contracts for `dbc`
+//!
+//! This companion crate provides an attribute-macro front end for the
+//! [`dbc`](https://crates.io/crates/dbc) design-by-contract macros. Instead of
+//! hand-placing `require!`/`ensure!`/`invariant!` calls inside a function body,
+//! contracts can be written as declarative annotations on whole functions and
+//! `impl` blocks:
+//!
+//! ```ignore
+//! use dbc_macros::{require, ensure, invariant};
+//!
+//! #[require(x != 0)]
+//! #[ensure(result != 0)]
+//! fn double(x: i32) -> i32 {
+//!     x * 2
+//! }
+//!
+//! #[invariant]
+//! impl Rectangle {
+//!     fn area(&self) -> i32 {
+//!         self.length * self.width
+//!     }
+//! }
+//! ```
+//!
+//! Every check expands to the matching plain `dbc` macro (`require!`,
+//! `ensure!`, `invariant!`), so it is a hard check that stays enabled in
+//! optimized builds and is reported with `file!()`/`line!()` through the
+//! `dbc` violation handler. A postcondition that uses `old(...)` is the
+//! exception: it is only checked in debug builds (see [`ensure`]). The spans of the generated checks point at the annotated
+//! item, so a violation names the offending contract.
+//!
+//! The generated code refers to the `dbc` crate through absolute
+//! `::dbc::...` paths, so a crate using these attributes only needs `dbc` as
+//! a dependency — no `#[macro_use] extern crate dbc;` is required.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{format_ident, quote};
+use syn::visit_mut::{self, VisitMut};
+use syn::{
+    parse_macro_input, parse_quote, Block, Expr, FnArg, Ident, ImplItem, Item, ItemFn, ItemImpl,
+    Lifetime, ReturnType, Stmt, Type,
+};
+
+/// Precondition attribute.
+///
+/// `#[require(COND)]` injects a `require!(COND)` check at the top of the
+/// annotated function body. The condition is checked before any of the
+/// function's own statements run.
+#[proc_macro_attribute]
+pub fn require(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let cond = parse_macro_input!(attr as Expr);
+    let mut func = parse_macro_input!(item as ItemFn);
+
+    let check: Stmt = parse_quote!(::dbc::require!(#cond););
+    func.block.stmts.insert(0, check);
+
+    quote!(#func).into()
+}
+
+/// Postcondition attribute.
+///
+/// `#[ensure(COND)]` wraps the annotated function body so that the
+/// `ensure!(COND)` check runs on every `return` path as well as the tail
+/// expression. The original body is evaluated inside a labeled block and
+/// each of its `return`s is rewritten into a `break` out of that block
+/// (see `capture_body`), so the postcondition is always reached. The return value is bound to `result`,
+/// so `COND` may refer to it (for example `#[ensure(result != 0)]`).
+///
+/// `COND` may refer to pre-state with `old(EXPR)`. Each `old(EXPR)` is
+/// rewritten into a `let_old!` snapshot taken at function entry and the
+/// captured value is substituted back into the condition checked at exit.
+/// Multiple `old(...)` captures are evaluated left-to-right.
+///
+/// Note that `old(...)` snapshots are only captured in debug builds, so a
+/// postcondition that uses `old(...)` is itself debug-only, unlike a plain
+/// `#[ensure]` which is always checked. See [`dbc::let_old!`] for details.
+#[proc_macro_attribute]
+pub fn ensure(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut cond = parse_macro_input!(attr as Expr);
+    let mut func = parse_macro_input!(item as ItemFn);
+
+    let mut olds = OldCapture::default();
+    olds.visit_expr_mut(&mut cond);
+
+    let names = &olds.names;
+    let exprs = &olds.exprs;
+    let body = capture_body(&func.block);
+
+    *func.block = if names.is_empty() {
+        parse_quote!({
+            let result = #body;
+            ::dbc::ensure!(#cond);
+            result
+        })
+    } else {
+        parse_quote!({
+            #(::dbc::let_old!(#names = #exprs);)*
+            let result = #body;
+            ::dbc::ensure!(#cond; #(#names),*);
+            result
+        })
+    };
+
+    quote!(#func).into()
+}
+
+/// Rewrites a function body into a closure-free expression yielding its value.
+///
+/// The body is wrapped in a labeled block and every `return EXPR` belonging
+/// to the function is rewritten into `break '<label> EXPR`, so each return
+/// path and the tail expression both produce the block's value. Unlike a
+/// closure trampoline this does not capture `self`, so contracts keep
+/// compiling on methods that return a borrow of `self` or on `async fn`.
+fn capture_body(block: &Block) -> Expr {
+    let label = Lifetime::new("'__dbc_body", Span::call_site());
+    let mut body = block.clone();
+    ReturnRewriter { label: label.clone() }.visit_block_mut(&mut body);
+    parse_quote!(#label: #body)
+}
+
+/// Rewrites `return EXPR` into `break '<label> EXPR` for the returns that
+/// belong to the annotated function, leaving returns in nested closures,
+/// `async` blocks and items untouched.
+struct ReturnRewriter {
+    label: Lifetime,
+}
+
+impl VisitMut for ReturnRewriter {
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        match expr {
+            // Returns inside these constructs belong to a different scope.
+            Expr::Closure(_) | Expr::Async(_) => return,
+            Expr::Return(ret) => {
+                let label = &self.label;
+                let value = match ret.expr.take() {
+                    Some(value) => quote!(#value),
+                    None => quote!(()),
+                };
+                *expr = parse_quote!(break #label #value);
+                return;
+            }
+            _ => {}
+        }
+        visit_mut::visit_expr_mut(self, expr);
+    }
+
+    fn visit_item_mut(&mut self, _item: &mut Item) {
+        // Nested items carry their own returns.
+    }
+}
+
+/// Rewrites each `old(EXPR)` call in a postcondition into a reference to a
+/// freshly generated snapshot identifier, recording the captures in
+/// left-to-right order.
+#[derive(Default)]
+struct OldCapture {
+    names: Vec<Ident>,
+    exprs: Vec<Expr>,
+}
+
+impl VisitMut for OldCapture {
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        // Descend first so nested `old(...)` calls are captured in source order.
+        visit_mut::visit_expr_mut(self, expr);
+
+        if let Expr::Call(call) = expr {
+            if is_old_path(&call.func) && call.args.len() == 1 {
+                let name = format_ident!("__dbc_old_{}", self.names.len());
+                self.exprs.push(call.args[0].clone());
+                let replacement: Expr = parse_quote!(#name);
+                self.names.push(name);
+                *expr = replacement;
+            }
+        }
+    }
+}
+
+/// Returns `true` when `func` is a bare path named `old`.
+fn is_old_path(func: &Expr) -> bool {
+    matches!(func, Expr::Path(path) if path.path.is_ident("old"))
+}
+
+/// Invariant attribute for an `impl` block.
+///
+/// `#[invariant]` emits an `invariant!(self)` check at entry and exit of every
+/// method that takes `&self` or `&mut self`. The receiving type must implement
+/// the [`dbc::Invariant`](../dbc/trait.Invariant.html) trait.
+///
+/// Methods that return a reference (for example a `&mut self` getter yielding
+/// `&mut self.field`) only get the entry check: the returned borrow keeps
+/// `self` borrowed past the method, so an exit check could not borrow `self`
+/// again.
+#[proc_macro_attribute]
+pub fn invariant(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut block = parse_macro_input!(item as ItemImpl);
+
+    for item in &mut block.items {
+        if let ImplItem::Fn(method) = item {
+            if takes_self(method.sig.inputs.first()) {
+                let body = capture_body(&method.block);
+                method.block = if returns_reference(&method.sig.output) {
+                    parse_quote!({
+                        ::dbc::invariant!(self);
+                        #body
+                    })
+                } else {
+                    parse_quote!({
+                        ::dbc::invariant!(self);
+                        let result = #body;
+                        ::dbc::invariant!(self);
+                        result
+                    })
+                };
+            }
+        }
+    }
+
+    quote!(#block).into()
+}
+
+/// Returns `true` when the first argument is a `&self` or `&mut self` receiver.
+fn takes_self(arg: Option<&FnArg>) -> bool {
+    matches!(arg, Some(FnArg::Receiver(recv)) if matches!(*recv.ty, Type::Reference(_)))
+}
+
+/// Returns `true` when the method returns a reference type.
+fn returns_reference(output: &ReturnType) -> bool {
+    matches!(output, ReturnType::Type(_, ty) if matches!(**ty, Type::Reference(_)))
+}
@@ -0,0 +1,113 @@
+// Copyright 2017 Luis Pabón <lpabon@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use dbc::Invariant;
+use dbc_macros::{ensure, invariant, require};
+
+#[require(x != 0)]
+#[ensure(result != 0)]
+fn double(x: i32) -> i32 {
+    x * 2
+}
+
+#[ensure(result == old(n) + 1)]
+fn incr(n: i32) -> i32 {
+    n + 1
+}
+
+// Reference return plus an early `return`: the closure trampoline used to
+// reject this, the labeled-block rewrite must accept it.
+#[ensure(*result == 2)]
+fn find_two(v: &[i32]) -> &i32 {
+    for x in v {
+        if *x == 2 {
+            return x;
+        }
+    }
+    &v[0]
+}
+
+#[derive(Debug)]
+struct Counter {
+    count: i32,
+}
+
+#[invariant]
+impl Counter {
+    fn bump(&mut self) {
+        self.count += 1;
+    }
+
+    fn set(&mut self, value: i32) {
+        self.count = value;
+    }
+
+    fn get(&self) -> i32 {
+        self.count
+    }
+
+    // Returns a borrow of `self`; only the entry invariant is emitted.
+    fn count_mut(&mut self) -> &mut i32 {
+        &mut self.count
+    }
+}
+
+impl Invariant for Counter {
+    fn invariant(&self) -> bool {
+        self.count >= 0
+    }
+}
+
+#[test]
+fn require_and_ensure_pass() {
+    assert_eq!(double(21), 42);
+}
+
+#[test]
+#[should_panic]
+fn require_precondition_fails() {
+    double(0);
+}
+
+#[test]
+fn old_postcondition_passes() {
+    assert_eq!(incr(4), 5);
+}
+
+#[test]
+fn ensure_reference_return_with_early_return() {
+    assert_eq!(*find_two(&[1, 2, 3]), 2);
+}
+
+#[test]
+fn invariant_methods_run() {
+    let mut c = Counter { count: 0 };
+    c.bump();
+    assert_eq!(c.get(), 1);
+}
+
+#[test]
+fn invariant_reference_return_compiles() {
+    let mut c = Counter { count: 0 };
+    *c.count_mut() = 5;
+    assert_eq!(c.get(), 5);
+}
+
+#[test]
+#[should_panic]
+fn invariant_exit_violation_panics() {
+    let mut c = Counter { count: 0 };
+    c.set(-1);
+}
@@ -21,10 +21,14 @@
 //! `{P} C {Q}`, where `{P}` is the precondition before
 //! executing command `C`, and `{Q}` is the postcondition.
 //!
-//! Like `debug_assert!`, dbc macros are only enabled in non
-//! optimized builds by default. An optimized build will omit all
-//! dbc macro statements unless `-C debug-assertions` is passed to the
-//! compiler.
+//! Each contract comes in three severity modes. The plain macros
+//! (`require!`, `ensure!`, `invariant!`) are hard checks that are always
+//! enabled, even in optimized builds. The `debug_*` variants behave like
+//! `debug_assert!` and are only enabled in non optimized builds unless
+//! `-C debug-assertions` is passed to the compiler. The `test_*` variants
+//! are guarded by `cfg!(test)` and only run under `cargo test`, which is
+//! useful for checking a fast implementation against a slow but obviously
+//! correct reference.
 //!
 //! ## See Also
 //!
@@ -37,23 +41,146 @@
 //! for Golang.
 //!
 
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The kind of contract that failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContractKind {
+    Require,
+    Ensure,
+    Invariant,
+}
+
+impl ContractKind {
+    /// The header printed by the default handler for this kind.
+    fn header(self) -> &'static str {
+        match self {
+            ContractKind::Require => "REQUIRE",
+            ContractKind::Ensure => "ENSURE",
+            ContractKind::Invariant => "INVARIANT",
+        }
+    }
+}
+
+/// The severity mode under which a contract was checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContractMode {
+    /// A hard check that is always enabled (`require!`, `ensure!`, ...).
+    Always,
+    /// A check enabled only in debug builds (`debug_require!`, ...).
+    Debug,
+    /// A check enabled only under `cargo test` (`test_require!`, ...).
+    Test,
+}
+
+impl ContractMode {
+    /// The suffix appended to the header for this mode.
+    fn suffix(self) -> &'static str {
+        match self {
+            ContractMode::Always => "",
+            ContractMode::Debug => "(debug)",
+            ContractMode::Test => "(test)",
+        }
+    }
+}
+
+/// A description of a failed contract handed to the violation handler.
+///
+/// The `vars` field holds the already formatted `formatvar!` dump, or the
+/// empty string when no variables were supplied to the contract macro.
+#[derive(Debug, Clone)]
+pub struct ContractViolation {
+    pub kind: ContractKind,
+    pub mode: ContractMode,
+    pub file: &'static str,
+    pub line: u32,
+    pub vars: String,
+}
+
+/// A function invoked whenever a contract is violated.
+pub type ViolationHandler = fn(&ContractViolation);
+
+// The active handler, stored as a raw function-pointer address. A value of
+// `0` means no handler has been installed and `default_handler` is used.
+static HANDLER: AtomicUsize = AtomicUsize::new(0);
+
+/// Install a custom contract-violation handler.
+///
+/// The handler is called by every contract macro when a check fails, in
+/// place of the default print-and-panic behavior. Embedders that must not
+/// abort the process can use this to log and continue, or to unwind with a
+/// typed payload of their own.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate dbc;
+/// use dbc::{set_violation_handler, ContractViolation};
+///
+/// fn log_only(v: &ContractViolation) {
+///     eprintln!("contract {:?} failed at {}:{}", v.kind, v.file, v.line);
+/// }
+///
+/// # fn main() {
+/// set_violation_handler(log_only);
+/// # }
+/// ```
+pub fn set_violation_handler(handler: ViolationHandler) {
+    HANDLER.store(handler as usize, Ordering::SeqCst);
+}
+
+/// The default handler: print the violation and panic.
+fn default_handler(violation: &ContractViolation) {
+    use std::env;
+    let header = violation.kind.header();
+    let suffix = violation.mode.suffix();
+    println!("panic: {}{}: \nfile: {}:{}",
+        header, suffix, violation.file, violation.line);
+    if !violation.vars.is_empty() {
+        println!("vars:\n{}", violation.vars);
+    }
+    env::set_var("RUST_BACKTRACE", "1");
+    panic!("{}{} contract violated", header, suffix);
+}
+
+/// Dispatch a contract violation through the installed handler.
+///
+/// This is an implementation detail used by the contract macros and is not
+/// intended to be called directly.
+#[doc(hidden)]
+pub fn report_violation(violation: &ContractViolation) {
+    let raw = HANDLER.load(Ordering::SeqCst);
+    if raw == 0 {
+        default_handler(violation);
+    } else {
+        // Safe: `raw` was produced from a `ViolationHandler` by
+        // `set_violation_handler` and function-pointer addresses round-trip
+        // through `usize`.
+        let handler: ViolationHandler = unsafe { std::mem::transmute(raw) };
+        handler(violation);
+    }
+}
+
 #[doc(hidden)]
 #[macro_export]
 macro_rules! dbc_panic {
-    ($type:expr, $cond:expr) => (if !$cond {
-        use std::env;
-        println!("panic: {}: \nfile: {}:{}",
-            $type, file!(), line!());
-        env::set_var("RUST_BACKTRACE", "1");
-        assert!($cond);
+    ($enabled:expr, $mode:expr, $kind:expr, $cond:expr) => (if $enabled && !$cond {
+        $crate::report_violation(&$crate::ContractViolation {
+            kind: $kind,
+            mode: $mode,
+            file: file!(),
+            line: line!(),
+            vars: String::new(),
+        });
     });
-    ($type:expr, $cond:expr, $($args:tt)*) => (if !$cond {
-        use std::env;
-        println!("panic: {}: \nfile: {}:{}",
-            $type, file!(), line!());
-        println!("vars:\n{}", formatvar!($($args)*));
-        env::set_var("RUST_BACKTRACE", "1");
-        assert!($cond);
+    ($enabled:expr, $mode:expr, $kind:expr, $cond:expr, $($args:tt)*) => (if $enabled && !$cond {
+        $crate::report_violation(&$crate::ContractViolation {
+            kind: $kind,
+            mode: $mode,
+            file: file!(),
+            line: line!(),
+            vars: $crate::formatvar!($($args)*),
+        });
     })
 }
 
@@ -92,7 +219,7 @@ macro_rules! dbc_panic {
 #[macro_export]
 macro_rules! formatvar {
     ($var:ident) => (format!("{}={:?}", stringify!($var), $var));
-    ($var:ident, $($arg:tt)*) => (format!("{} {}", formatvar!($var), formatvar!($($arg)*)));
+    ($var:ident, $($arg:tt)*) => (format!("{} {}", $crate::formatvar!($var), $crate::formatvar!($($arg)*)));
 }
 
 /// Precondondition tests
@@ -127,14 +254,94 @@ macro_rules! formatvar {
 /// ```
 #[macro_export]
 macro_rules! require {
-    ($cond:expr) => (if cfg!(debug_assertions) {
-        dbc_panic!("REQUIRE", $cond)
-    });
-    ($cond:expr, $($args:tt)*) => (if cfg!(debug_assertions) {
-        dbc_panic!("REQUIRE", $cond, $($args)*)
+    ($cond:expr) => ($crate::dbc_panic!(true, $crate::ContractMode::Always, $crate::ContractKind::Require, $cond));
+    ($cond:expr, $($args:tt)*) => ($crate::dbc_panic!(true, $crate::ContractMode::Always, $crate::ContractKind::Require, $cond, $($args)*))
+}
+
+/// Precondition tests enabled only in debug builds
+///
+/// Like `require!` but, mirroring `debug_assert!`, the check is compiled out
+/// of optimized builds unless `-C debug-assertions` is passed to the compiler.
+#[macro_export]
+macro_rules! debug_require {
+    ($cond:expr) => ($crate::dbc_panic!(cfg!(debug_assertions), $crate::ContractMode::Debug, $crate::ContractKind::Require, $cond));
+    ($cond:expr, $($args:tt)*) =>
+        ($crate::dbc_panic!(cfg!(debug_assertions), $crate::ContractMode::Debug, $crate::ContractKind::Require, $cond, $($args)*))
+}
+
+/// Precondition tests enabled only under `cargo test`
+///
+/// The check is guarded by `cfg!(test)`, so it only runs while the test
+/// harness is active. This is handy for validating a fast implementation
+/// against a slower but obviously correct reference during tests.
+#[macro_export]
+macro_rules! test_require {
+    ($cond:expr) => ($crate::dbc_panic!(cfg!(test), $crate::ContractMode::Test, $crate::ContractKind::Require, $cond));
+    ($cond:expr, $($args:tt)*) =>
+        ($crate::dbc_panic!(cfg!(test), $crate::ContractMode::Test, $crate::ContractKind::Require, $cond, $($args)*))
+}
+
+/// Precondition that two values are equal
+///
+/// Like `assert_eq!`, `require_eq!(a, b)` asserts that `a == b` and, on
+/// failure, prints both operands using their `Debug` representations so the
+/// offending values need not be listed by hand. The operands are reborrowed
+/// into `left`/`right` so they are only evaluated once.
+///
+/// # Examples
+///
+/// ```should_panic
+/// # #[macro_use] extern crate dbc;
+/// # fn main() {
+/// require_eq!(1 + 1, 2);
+/// require_eq!(1 + 1, 3);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! require_eq {
+    ($left:expr, $right:expr) => (match (&$left, &$right) {
+        (left, right) => $crate::dbc_panic!(true, $crate::ContractMode::Always, $crate::ContractKind::Require, *left == *right, left, right)
+    })
+}
+
+/// Precondition that two values are not equal
+///
+/// The counterpart to `require_eq!`: asserts that `a != b` and prints both
+/// operands on failure.
+#[macro_export]
+macro_rules! require_ne {
+    ($left:expr, $right:expr) => (match (&$left, &$right) {
+        (left, right) => $crate::dbc_panic!(true, $crate::ContractMode::Always, $crate::ContractKind::Require, *left != *right, left, right)
     })
 }
 
+/// Compile-time precondition
+///
+/// `static_require!(CONST_EXPR)` evaluates a boolean const expression while
+/// the crate is being compiled and fails the build, rather than at runtime,
+/// when the condition is false. Unlike `debug_require!` it is unaffected by
+/// `debug_assertions`, which makes it the right tool for contracts over
+/// constants and `const fn` inputs.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate dbc;
+/// const WORD: usize = 8;
+/// static_require!(WORD.is_power_of_two());
+/// # fn main() {}
+/// ```
+///
+/// A violated condition such as `static_require!(WORD == 7)` would abort the
+/// build with a message naming the failed static precondition.
+#[macro_export]
+macro_rules! static_require {
+    ($cond:expr) => {
+        const _: () = assert!($cond,
+            concat!("static_require failed: ", stringify!($cond)));
+    };
+}
+
 /// Postcondition tests
 ///
 /// This macro is used to assert postconditions. Any variables passed
@@ -169,14 +376,116 @@ macro_rules! require {
 /// ```
 #[macro_export]
 macro_rules! ensure {
-    ($cond:expr) => (if cfg!(debug_assertions) {
-        dbc_panic!("ENSURE", $cond)
+    ($cond:expr) => ($crate::dbc_panic!(true, $crate::ContractMode::Always, $crate::ContractKind::Ensure, $cond));
+    ($cond:expr, $($args:tt)*) => ($crate::dbc_panic!(true, $crate::ContractMode::Always, $crate::ContractKind::Ensure, $cond, $($args)*));
+    // The `old` snapshots are only captured by `let_old!` in debug builds, so
+    // these arms are debug-only: the pre-state does not exist in release. See
+    // the `let_old!` docs for the rationale.
+    ($cond:expr; $($old:ident),+ $(,)?) => (if cfg!(debug_assertions) {
+        $(let $old = $old.expect("let_old! snapshot missing in debug build");)+
+        $crate::dbc_panic!(true, $crate::ContractMode::Debug, $crate::ContractKind::Ensure, $cond)
     });
-    ($cond:expr, $($args:tt)*) => (if cfg!(debug_assertions) {
-        dbc_panic!("ENSURE", $cond, $($args)*)
+    ($cond:expr; $($old:ident),+ ; $($args:tt)*) => (if cfg!(debug_assertions) {
+        $(let $old = $old.expect("let_old! snapshot missing in debug build");)+
+        $crate::dbc_panic!(true, $crate::ContractMode::Debug, $crate::ContractKind::Ensure, $cond, $($args)*)
     })
 }
 
+/// Postcondition tests enabled only in debug builds
+///
+/// Like `ensure!` but compiled out of optimized builds unless
+/// `-C debug-assertions` is passed to the compiler.
+#[macro_export]
+macro_rules! debug_ensure {
+    ($cond:expr) => ($crate::dbc_panic!(cfg!(debug_assertions), $crate::ContractMode::Debug, $crate::ContractKind::Ensure, $cond));
+    ($cond:expr, $($args:tt)*) =>
+        ($crate::dbc_panic!(cfg!(debug_assertions), $crate::ContractMode::Debug, $crate::ContractKind::Ensure, $cond, $($args)*))
+}
+
+/// Postcondition tests enabled only under `cargo test`
+///
+/// The check is guarded by `cfg!(test)`, so it only runs while the test
+/// harness is active.
+#[macro_export]
+macro_rules! test_ensure {
+    ($cond:expr) => ($crate::dbc_panic!(cfg!(test), $crate::ContractMode::Test, $crate::ContractKind::Ensure, $cond));
+    ($cond:expr, $($args:tt)*) =>
+        ($crate::dbc_panic!(cfg!(test), $crate::ContractMode::Test, $crate::ContractKind::Ensure, $cond, $($args)*))
+}
+
+/// Postcondition that two values are equal
+///
+/// Like `require_eq!` but for postconditions: asserts that `a == b` and, on
+/// failure, prints both operands using their `Debug` representations. The
+/// operands are reborrowed into `left`/`right` so they are only evaluated
+/// once.
+#[macro_export]
+macro_rules! ensure_eq {
+    ($left:expr, $right:expr) => (match (&$left, &$right) {
+        (left, right) => $crate::dbc_panic!(true, $crate::ContractMode::Always, $crate::ContractKind::Ensure, *left == *right, left, right)
+    })
+}
+
+/// Postcondition that two values are not equal
+///
+/// The counterpart to `ensure_eq!`: asserts that `a != b` and prints both
+/// operands on failure.
+#[macro_export]
+macro_rules! ensure_ne {
+    ($left:expr, $right:expr) => (match (&$left, &$right) {
+        (left, right) => $crate::dbc_panic!(true, $crate::ContractMode::Always, $crate::ContractKind::Ensure, *left != *right, left, right)
+    })
+}
+
+/// Snapshot an expression for use in a postcondition
+///
+/// Postconditions often need to compare against a value as it was *before*
+/// the function ran. `let_old!(name = expr)` clones `expr` (which must be
+/// `Clone`) into a hidden binding so that a later `ensure!` can reference
+/// `name`. Like the `debug_*` macros the snapshot is only taken when
+/// `debug_assertions` are enabled; in optimized builds the clone is never
+/// evaluated and the binding costs nothing.
+///
+/// The captured binding is passed to `ensure!` after a `;`, where it is
+/// unwrapped back into its value for the duration of the check.
+///
+/// Because the snapshot is only taken in debug builds, an `ensure!(...; old)`
+/// check that consumes one is itself debug-only. This is unlike the plain
+/// `ensure!($cond)` arm, which is always enabled: a postcondition that
+/// references `old` state cannot fire in an optimized build because the
+/// pre-state it compares against was never captured there. Reach for
+/// `ensure!` without `old` (or `debug_ensure!`) when you want the release
+/// behavior to be obvious at the call site.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate dbc;
+///
+/// fn push(v: &mut Vec<i32>, x: i32) {
+///     let_old!(old_len = v.len());
+///
+///     v.push(x);
+///
+///     ensure!(v.len() == old_len + 1; old_len);
+/// }
+///
+/// # fn main() {
+/// let mut v = vec![1, 2, 3];
+/// push(&mut v, 4);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! let_old {
+    ($name:ident = $expr:expr) => {
+        let $name = if cfg!(debug_assertions) {
+            Some(($expr).clone())
+        } else {
+            None
+        };
+    };
+}
+
 /// The `Invariant` trait allows for asserting an object
 ///
 /// Implementors of the `Invariant` trait can then use the `invariant!`
@@ -230,12 +539,33 @@ pub trait Invariant {
 /// ```
 #[macro_export]
 macro_rules! invariant {
-    ($obj:ident) => (if cfg!(debug_assertions){
-        dbc_panic!("INVARIANT", $obj.invariant(), $obj)
-    });
-    ($obj:ident, $($args:tt)*) => (if cfg!(debug_assertions){
-        dbc_panic!("INVARIANT", $obj.invariant(), $obj, $($args)*)
-    })
+    ($obj:ident) => ($crate::dbc_panic!(true, $crate::ContractMode::Always, $crate::ContractKind::Invariant, $obj.invariant(), $obj));
+    ($obj:ident, $($args:tt)*) =>
+        ($crate::dbc_panic!(true, $crate::ContractMode::Always, $crate::ContractKind::Invariant, $obj.invariant(), $obj, $($args)*))
+}
+
+/// Invariant condition assertion enabled only in debug builds
+///
+/// Like `invariant!` but compiled out of optimized builds unless
+/// `-C debug-assertions` is passed to the compiler.
+#[macro_export]
+macro_rules! debug_invariant {
+    ($obj:ident) =>
+        ($crate::dbc_panic!(cfg!(debug_assertions), $crate::ContractMode::Debug, $crate::ContractKind::Invariant, $obj.invariant(), $obj));
+    ($obj:ident, $($args:tt)*) =>
+        ($crate::dbc_panic!(cfg!(debug_assertions), $crate::ContractMode::Debug, $crate::ContractKind::Invariant, $obj.invariant(), $obj, $($args)*))
+}
+
+/// Invariant condition assertion enabled only under `cargo test`
+///
+/// The check is guarded by `cfg!(test)`, so it only runs while the test
+/// harness is active.
+#[macro_export]
+macro_rules! test_invariant {
+    ($obj:ident) =>
+        ($crate::dbc_panic!(cfg!(test), $crate::ContractMode::Test, $crate::ContractKind::Invariant, $obj.invariant(), $obj));
+    ($obj:ident, $($args:tt)*) =>
+        ($crate::dbc_panic!(cfg!(test), $crate::ContractMode::Test, $crate::ContractKind::Invariant, $obj.invariant(), $obj, $($args)*))
 }
 
 #[cfg(test)]
@@ -295,6 +625,77 @@ mod tests {
         assert!(formatvar!(msg,a,b) == "msg=\"My message\" a=34 b=BB(AA(234))");
     }
 
+    #[test]
+    #[should_panic]
+    fn test_debug_require_asserts() {
+        debug_require!(false);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_test_require_asserts() {
+        test_require!(false);
+    }
+
+    #[test]
+    fn test_test_require_does_not_assert() {
+        test_require!(true);
+    }
+
+    #[test]
+    fn test_require_eq_does_not_assert() {
+        require_eq!(1 + 1, 2);
+        require_ne!(1 + 1, 3);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_require_eq_asserts() {
+        require_eq!(1 + 1, 3);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_ensure_ne_asserts() {
+        ensure_ne!(2, 2);
+    }
+
+    #[test]
+    fn test_let_old() {
+        fn push(v: &mut Vec<i32>, x: i32) {
+            let_old!(old_len = v.len());
+
+            v.push(x);
+
+            ensure!(v.len() == old_len + 1; old_len);
+        }
+
+        let mut v = vec![1, 2, 3];
+        push(&mut v, 4);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_let_old_asserts() {
+        fn broken(v: &mut Vec<i32>, x: i32) {
+            let_old!(old_len = v.len());
+
+            v.push(x);
+
+            ensure!(v.len() == old_len; old_len);
+        }
+
+        let mut v = vec![1, 2, 3];
+        broken(&mut v, 4);
+    }
+
+    #[test]
+    fn test_static_require() {
+        const WORD: usize = 8;
+        static_require!(WORD.is_power_of_two());
+        static_require!(WORD == 8);
+    }
+
     #[test]
     fn test_invariant() {
         let r = Rectangle{